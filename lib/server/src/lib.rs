@@ -9,6 +9,7 @@ extern crate uuid;
 #[macro_use]
 extern crate serde_derive;
 extern crate crypto;
+extern crate reqwest;
 extern crate rustc_serialize;
 extern crate serde_json;
 
@@ -18,15 +19,41 @@ extern crate trow_protobuf;
 
 pub mod manifest;
 mod server;
+mod store;
 mod validate;
 use failure::Error;
 use futures::Future;
 use grpcio::{Environment, ServerBuilder};
 use server::TrowService;
+use std::sync::Arc;
 use std::thread;
 
-pub fn start_server(data_path: &str, listen_addr: &str, listen_port: u16) {
-    match server_async(data_path, listen_addr, listen_port) {
+pub use store::{ByteStream, FileStore, FinalizeOutcome, GcReport, S3Credentials, S3Store, Store};
+
+/*
+Which `Store` implementation backs blob/manifest persistence. Defaults to
+the local filesystem; `S3` lets a cluster of Trow instances share one
+object store instead of requiring a shared disk.
+*/
+pub enum StoreConfig {
+    FileSystem,
+    S3 { bucket: String, credentials: S3Credentials },
+}
+
+/// Default cap on the size of a single blob or manifest upload, in bytes,
+/// used when the caller does not configure one explicitly. Set generously
+/// above a typical layer size (4GiB) so existing deployments aren't broken
+/// by upgrading.
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+pub fn start_server(
+    data_path: &str,
+    store_config: StoreConfig,
+    listen_addr: &str,
+    listen_port: u16,
+    max_upload_bytes: u64,
+) {
+    match server_async(data_path, store_config, listen_addr, listen_port, max_upload_bytes) {
         Ok(mut server) => {
             thread::park();
             let _ = server.shutdown().wait();
@@ -39,17 +66,32 @@ pub fn start_server(data_path: &str, listen_addr: &str, listen_port: u16) {
     }
 }
 
+/// Builds the `Store` `store_config` selects. `data_path` is still needed
+/// even for `StoreConfig::S3`, since in-progress chunked uploads are always
+/// staged on local disk (see `S3Store`'s doc comment) regardless of where
+/// finalized blobs/manifests end up.
+fn build_store(data_path: &str, store_config: StoreConfig) -> Arc<dyn Store> {
+    match store_config {
+        StoreConfig::FileSystem => Arc::new(FileStore::new(data_path)),
+        StoreConfig::S3 { bucket, credentials } => {
+            Arc::new(S3Store::new(&bucket, credentials, data_path))
+        }
+    }
+}
+
 pub fn server_async(
     data_path: &str,
+    store_config: StoreConfig,
     listen_addr: &str,
     listen_port: u16,
+    max_upload_bytes: u64,
 ) -> Result<grpcio::Server, Error> {
-    use std::sync::Arc;
-
     debug!("Setting up Trow server");
     let env = Arc::new(Environment::new(1));
 
-    let trow_service = trow_protobuf::server_grpc::create_registry(TrowService::new(data_path)?);
+    let store = build_store(data_path, store_config);
+    let trow_service =
+        trow_protobuf::server_grpc::create_registry(TrowService::new(store, max_upload_bytes)?);
 
     let mut server = ServerBuilder::new(env)
         .register_service(trow_service)
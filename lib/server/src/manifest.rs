@@ -0,0 +1,141 @@
+use serde_json::Value;
+
+/// Parses a manifest body into the typed shape that matches its media type,
+/// so callers can distinguish schema1/schema2 image manifests from manifest
+/// lists instead of assuming every push looks like a schema1 manifest.
+pub trait FromJson: Sized {
+    fn from_json(json: &Value) -> Result<Self, String>;
+}
+
+static DOCKER_MANIFEST_V2: &'static str = "application/vnd.docker.distribution.manifest.v2+json";
+static DOCKER_MANIFEST_LIST_V2: &'static str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+static OCI_MANIFEST_V1: &'static str = "application/vnd.oci.image.manifest.v1+json";
+static OCI_INDEX_V1: &'static str = "application/vnd.oci.image.index.v1+json";
+
+#[derive(Debug, Clone)]
+pub enum Manifest {
+    Schema1(Schema1Manifest),
+    Schema2(Schema2Manifest),
+    List(ManifestList),
+}
+
+impl Manifest {
+    /// Digests this manifest references: layer (and config) blob digests for
+    /// an image manifest, or child manifest digests for a manifest list.
+    /// Callers that need to tell the two apart (existence-checking blobs vs
+    /// manifests) should match on the variant directly.
+    pub fn get_asset_digests(&self) -> Vec<String> {
+        match *self {
+            Manifest::Schema1(ref m) => m.fs_layers.clone(),
+            Manifest::Schema2(ref m) => {
+                let mut digests = m.layers.clone();
+                digests.push(m.config_digest.clone());
+                digests
+            }
+            Manifest::List(ref l) => l.manifests.clone(),
+        }
+    }
+}
+
+impl FromJson for Manifest {
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let media_type = json.get("mediaType").and_then(Value::as_str).unwrap_or("");
+
+        if media_type == DOCKER_MANIFEST_LIST_V2 || media_type == OCI_INDEX_V1 {
+            return ManifestList::from_json(json).map(Manifest::List);
+        }
+        if media_type == DOCKER_MANIFEST_V2 || media_type == OCI_MANIFEST_V1 {
+            return Schema2Manifest::from_json(json).map(Manifest::Schema2);
+        }
+
+        // No (or unrecognized) mediaType: fall back to the shape of the
+        // body, since older clients push schema1 without setting one.
+        if json.get("manifests").is_some() {
+            ManifestList::from_json(json).map(Manifest::List)
+        } else if json.get("fsLayers").is_some() {
+            Schema1Manifest::from_json(json).map(Manifest::Schema1)
+        } else if json.get("layers").is_some() {
+            Schema2Manifest::from_json(json).map(Manifest::Schema2)
+        } else {
+            Err("unrecognized manifest shape".to_owned())
+        }
+    }
+}
+
+/// Docker schema1 (`application/vnd.docker.distribution.manifest.v1+json`):
+/// layers are listed newest-first under `fsLayers`, each a `{"blobSum": ...}`.
+#[derive(Debug, Clone)]
+pub struct Schema1Manifest {
+    pub fs_layers: Vec<String>,
+}
+
+impl FromJson for Schema1Manifest {
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let fs_layers = json
+            .get("fsLayers")
+            .and_then(Value::as_array)
+            .ok_or_else(|| "missing fsLayers".to_owned())?
+            .iter()
+            .map(|l| l.get("blobSum").and_then(Value::as_str).map(str::to_owned))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| "fsLayers entry missing blobSum".to_owned())?;
+
+        Ok(Schema1Manifest { fs_layers })
+    }
+}
+
+/// Docker schema2 / OCI image manifest: a single `config` blob plus ordered
+/// `layers`, each `{"digest": ...}`.
+#[derive(Debug, Clone)]
+pub struct Schema2Manifest {
+    pub config_digest: String,
+    pub layers: Vec<String>,
+}
+
+impl FromJson for Schema2Manifest {
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let config_digest = json
+            .get("config")
+            .and_then(|c| c.get("digest"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| "missing config.digest".to_owned())?
+            .to_owned();
+
+        let layers = json
+            .get("layers")
+            .and_then(Value::as_array)
+            .ok_or_else(|| "missing layers".to_owned())?
+            .iter()
+            .map(|l| l.get("digest").and_then(Value::as_str).map(str::to_owned))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| "layers entry missing digest".to_owned())?;
+
+        Ok(Schema2Manifest {
+            config_digest,
+            layers,
+        })
+    }
+}
+
+/// Docker manifest list / OCI image index: a set of child manifests, each
+/// `{"digest": ...}`, one per platform.
+#[derive(Debug, Clone)]
+pub struct ManifestList {
+    pub manifests: Vec<String>,
+}
+
+impl FromJson for ManifestList {
+    fn from_json(json: &Value) -> Result<Self, String> {
+        let manifests = json
+            .get("manifests")
+            .and_then(Value::as_array)
+            .ok_or_else(|| "missing manifests".to_owned())?
+            .iter()
+            .map(|m| m.get("digest").and_then(Value::as_str).map(str::to_owned))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| "manifests entry missing digest".to_owned())?;
+
+        Ok(ManifestList { manifests })
+    }
+}
@@ -0,0 +1,850 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use futures::Stream;
+use reqwest::{Client, Method, StatusCode};
+
+/// Blobs renamed into the content-addressed store more recently than this
+/// are left alone by `garbage_collect`, so a concurrent upload that has
+/// just finalized can't be swept before its manifest is written.
+const GC_GRACE_PERIOD_SECS: u64 = 60;
+
+pub struct GcReport {
+    pub blobs_removed: Vec<String>,
+}
+
+/*
+Abstraction over where blob and manifest bytes actually live. Every route
+handler used to hard-code `data/...` paths and touch `fs` directly, which
+meant Trow could only ever run against local disk. Blob handlers go through
+`ClientInterface`/`TrowService`, which holds an `Arc<dyn Store>` and never
+sees a filesystem path itself. Manifest handlers in the frontend's `routes`
+still read and write `data/manifests` directly and do not call this trait's
+manifest methods.
+*/
+
+pub type ByteStream = Box<dyn Stream<Item = Vec<u8>, Error = io::Error> + Send>;
+
+pub trait Store: Send + Sync {
+    /// True if `repo_name` is allowed to serve `digest`, i.e. it has been
+    /// linked into that repo's index, not merely present somewhere in the
+    /// shared store.
+    fn blob_exists(&self, repo_name: &str, digest: &str) -> io::Result<bool>;
+    fn read_blob(&self, repo_name: &str, digest: &str) -> io::Result<ByteStream>;
+    fn write_blob_sink(&self, uuid: &str) -> io::Result<Box<dyn io::Write + Send>>;
+    /// Bytes written so far for an in-progress upload (0 if `uuid` has no
+    /// scratch file yet), so a resumed chunked PATCH can report/validate
+    /// the true offset instead of assuming it always starts at 0.
+    fn upload_size(&self, uuid: &str) -> io::Result<u64>;
+
+    /// Discards an in-progress upload's scratch data, e.g. once it's grown
+    /// past `max_upload_bytes` and the rest of the chunk isn't worth saving.
+    /// A no-op, not an error, if `uuid` has no scratch file.
+    fn abort_upload(&self, uuid: &str) -> io::Result<()>;
+    /// Hashes the upload written via `write_blob_sink`, and on a match moves
+    /// it into the content-addressed store keyed by `expected_digest` (a
+    /// no-op rename-or-skip if that digest is already stored by another
+    /// repo) and links `repo_name` to it. On a mismatch the scratch file is
+    /// removed and `FinalizeOutcome::DigestMismatch` is returned rather than
+    /// an `io::Error`, so the caller can tell a corrupt push apart from a
+    /// storage failure.
+    fn finalize_blob(
+        &self,
+        repo_name: &str,
+        uuid: &str,
+        expected_digest: &str,
+    ) -> io::Result<FinalizeOutcome>;
+
+    /// Size of the blob `repo_name` has linked to `digest`, once finalized.
+    fn blob_size(&self, repo_name: &str, digest: &str) -> io::Result<u64>;
+
+    /// Unlinks `digest` from `repo_name`. Does not touch the shared blob
+    /// itself; that only goes away once `garbage_collect` finds no manifest
+    /// referencing it.
+    fn delete_blob(&self, repo_name: &str, digest: &str) -> io::Result<()>;
+
+    fn manifest_exists(&self, repo_name: &str, reference: &str) -> io::Result<bool>;
+    fn read_manifest(&self, repo_name: &str, reference: &str) -> io::Result<ByteStream>;
+    fn write_manifest(&self, repo_name: &str, reference: &str, bytes: &[u8]) -> io::Result<()>;
+    fn delete_manifest(&self, repo_name: &str, reference: &str) -> io::Result<()>;
+
+    /// Mark-and-sweep over every stored manifest: removes any blob that no
+    /// manifest references any more, leaving blobs finalized within the
+    /// last `GC_GRACE_PERIOD_SECS` alone so a concurrent upload can't be
+    /// swept before its manifest lands.
+    fn garbage_collect(&self) -> io::Result<GcReport>;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FinalizeOutcome {
+    Ok,
+    DigestMismatch,
+}
+
+/*
+Wraps the `data/` layout. Blobs are content-addressed once, globally, under
+`data/blobs/sha256/<hex>` so the same layer pushed to many repos is stored
+on disk exactly once; `data/repo-blobs/<repo>/<digest>` are empty marker
+files recording which digests a repo is allowed to serve. Manifests stay
+per-repo under `data/manifests`, since unlike layers they aren't shared
+content.
+*/
+pub struct FileStore {
+    data_path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(data_path: &str) -> FileStore {
+        FileStore {
+            data_path: PathBuf::from(data_path),
+        }
+    }
+
+    fn blob_path(&self, digest: &str) -> PathBuf {
+        // digest is "sha256:<hex>"; split the algorithm into a directory so
+        // other algorithms can be added without a migration.
+        let mut parts = digest.splitn(2, ':');
+        let algo = parts.next().unwrap_or("sha256");
+        let hex = parts.next().unwrap_or(digest);
+        self.data_path.join("blobs").join(algo).join(hex)
+    }
+
+    fn repo_blob_marker(&self, repo_name: &str, digest: &str) -> PathBuf {
+        self.data_path
+            .join("repo-blobs")
+            .join(repo_name)
+            .join(digest)
+    }
+
+    fn manifest_path(&self, repo_name: &str, reference: &str) -> PathBuf {
+        self.data_path
+            .join("manifests")
+            .join(repo_name)
+            .join(reference)
+    }
+}
+
+impl Store for FileStore {
+    fn blob_exists(&self, repo_name: &str, digest: &str) -> io::Result<bool> {
+        Ok(self.repo_blob_marker(repo_name, digest).exists())
+    }
+
+    fn read_blob(&self, repo_name: &str, digest: &str) -> io::Result<ByteStream> {
+        if !self.blob_exists(repo_name, digest)? {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "blob not in repo"));
+        }
+        let bytes = fs::read(self.blob_path(digest))?;
+        Ok(Box::new(futures::stream::once(Ok(bytes))))
+    }
+
+    fn write_blob_sink(&self, uuid: &str) -> io::Result<Box<dyn io::Write + Send>> {
+        let scratch_dir = self.data_path.join("scratch");
+        fs::create_dir_all(&scratch_dir)?;
+        // Opened for append, not truncated: a chunked upload calls this once
+        // per PATCH, and each chunk must land after the bytes already
+        // written by the previous one.
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(scratch_dir.join(uuid))?;
+        Ok(Box::new(file))
+    }
+
+    fn upload_size(&self, uuid: &str) -> io::Result<u64> {
+        match fs::metadata(self.data_path.join("scratch").join(uuid)) {
+            Ok(meta) => Ok(meta.len()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn abort_upload(&self, uuid: &str) -> io::Result<()> {
+        match fs::remove_file(self.data_path.join("scratch").join(uuid)) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn finalize_blob(
+        &self,
+        repo_name: &str,
+        uuid: &str,
+        expected_digest: &str,
+    ) -> io::Result<FinalizeOutcome> {
+        let scratch = self.data_path.join("scratch").join(uuid);
+
+        let mut hasher = Sha256::new();
+        let mut file = fs::File::open(&scratch)?;
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.input(&buf[..n]);
+        }
+        let actual_digest = format!("sha256:{}", hasher.result_str());
+        if actual_digest != expected_digest {
+            fs::remove_file(&scratch)?;
+            return Ok(FinalizeOutcome::DigestMismatch);
+        }
+
+        let dest = self.blob_path(expected_digest);
+        if dest.exists() {
+            // Another repo already has this digest: no need to store the
+            // bytes again, just drop the freshly uploaded duplicate.
+            fs::remove_file(&scratch)?;
+        } else {
+            fs::create_dir_all(dest.parent().unwrap())?;
+            fs::rename(&scratch, &dest)?;
+        }
+
+        let marker = self.repo_blob_marker(repo_name, expected_digest);
+        fs::create_dir_all(marker.parent().unwrap())?;
+        fs::write(marker, b"")?;
+        Ok(FinalizeOutcome::Ok)
+    }
+
+    fn blob_size(&self, repo_name: &str, digest: &str) -> io::Result<u64> {
+        if !self.blob_exists(repo_name, digest)? {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "blob not in repo"));
+        }
+        Ok(fs::metadata(self.blob_path(digest))?.len())
+    }
+
+    fn delete_blob(&self, repo_name: &str, digest: &str) -> io::Result<()> {
+        fs::remove_file(self.repo_blob_marker(repo_name, digest))
+    }
+
+    fn manifest_exists(&self, repo_name: &str, reference: &str) -> io::Result<bool> {
+        Ok(self.manifest_path(repo_name, reference).exists())
+    }
+
+    fn read_manifest(&self, repo_name: &str, reference: &str) -> io::Result<ByteStream> {
+        let bytes = fs::read(self.manifest_path(repo_name, reference))?;
+        Ok(Box::new(futures::stream::once(Ok(bytes))))
+    }
+
+    fn write_manifest(&self, repo_name: &str, reference: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.manifest_path(repo_name, reference);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, bytes)
+    }
+
+    fn delete_manifest(&self, repo_name: &str, reference: &str) -> io::Result<()> {
+        fs::remove_file(self.manifest_path(repo_name, reference))
+    }
+
+    /*
+    Mark-and-sweep GC: walks every manifest under `data/manifests`, collects
+    every digest-shaped string found in them (a blunt stand-in for parsing
+    each one with the `manifest` module), and removes any blob under
+    `data/blobs` that no manifest references.
+    Blobs renamed into place within the last `GC_GRACE_PERIOD_SECS` are left
+    alone, so a concurrent upload can't be swept before its manifest lands.
+    */
+    fn garbage_collect(&self) -> io::Result<GcReport> {
+        let referenced = self.collect_referenced_digests()?;
+        let mut removed = Vec::new();
+
+        let blobs_root = self.data_path.join("blobs");
+        if blobs_root.exists() {
+            for algo_entry in fs::read_dir(&blobs_root)? {
+                let algo_path = algo_entry?.path();
+                let algo = match algo_path.file_name().and_then(|n| n.to_str()) {
+                    Some(a) => a.to_owned(),
+                    None => continue,
+                };
+
+                for hex_entry in fs::read_dir(&algo_path)? {
+                    let hex_entry = hex_entry?;
+                    let hex = match hex_entry.file_name().into_string() {
+                        Ok(h) => h,
+                        Err(_) => continue,
+                    };
+                    let digest = format!("{}:{}", algo, hex);
+
+                    if referenced.contains(&digest) || self.recently_finalized(&hex_entry.path())? {
+                        continue;
+                    }
+
+                    fs::remove_file(hex_entry.path())?;
+                    removed.push(digest);
+                }
+            }
+        }
+
+        Ok(GcReport {
+            blobs_removed: removed,
+        })
+    }
+}
+
+impl FileStore {
+    fn recently_finalized(&self, path: &Path) -> io::Result<bool> {
+        let modified = fs::metadata(path)?.modified()?;
+        match SystemTime::now().duration_since(modified) {
+            Ok(age) => Ok(age.as_secs() < GC_GRACE_PERIOD_SECS),
+            Err(_) => Ok(true),
+        }
+    }
+
+    fn collect_referenced_digests(&self) -> io::Result<HashSet<String>> {
+        let mut digests = HashSet::new();
+        let manifests_root = self.data_path.join("manifests");
+        collect_digests_from_manifests(&manifests_root, &mut digests)?;
+        Ok(digests)
+    }
+}
+
+fn collect_digests_from_manifests(dir: &Path, digests: &mut HashSet<String>) -> io::Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_digests_from_manifests(&path, digests)?;
+        } else if path.extension().and_then(|e| e.to_str()) != Some("content-type") {
+            if let Ok(text) = fs::read_to_string(&path) {
+                digests.extend(extract_digests(&text));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_digests(text: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = text;
+
+    while let Some(idx) = rest.find("sha256:") {
+        let candidate = &rest[idx + 7..];
+        let hex_len = candidate
+            .chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .count();
+        if hex_len == 64 {
+            found.push(format!("sha256:{}", &candidate[..hex_len]));
+        }
+        rest = candidate;
+    }
+
+    found
+}
+
+/*
+Stores blobs and manifests in an S3-compatible bucket instead of on local
+disk, so a cluster of Trow instances can share one registry without a
+shared filesystem. Requests are signed with AWS Signature Version 4 (see
+`sign_and_send`), using the same HMAC-SHA256 primitives `routes::sign`
+uses for Bearer tokens, rather than pulling in a dedicated S3 SDK.
+
+In-progress uploads are still staged on local disk under `scratch_path`
+(S3 has no notion of appending to an object, and chunked PATCHes arrive
+one connection at a time), and are promoted to the bucket only once
+`finalize_blob` has hashed and verified them. Unlike `FileStore`, finalized
+blobs are not deduplicated across repos: each is stored per repo under
+`layers/<repo>/<digest>`, so the same digest pushed to two repos is stored
+twice, and `delete_blob` deletes that repo's copy outright.
+*/
+pub struct S3Store {
+    bucket: String,
+    credentials: S3Credentials,
+    client: Client,
+    scratch_path: PathBuf,
+}
+
+pub struct S3Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub endpoint: String,
+    pub region: String,
+}
+
+impl S3Store {
+    /// `scratch_path` is a local directory used only to stage uploads in
+    /// progress; finalized blobs and manifests live entirely in `bucket`.
+    pub fn new(bucket: &str, credentials: S3Credentials, scratch_path: &str) -> S3Store {
+        S3Store {
+            bucket: bucket.to_owned(),
+            credentials,
+            client: Client::new(),
+            scratch_path: PathBuf::from(scratch_path),
+        }
+    }
+
+    fn blob_key(&self, repo_name: &str, digest: &str) -> String {
+        format!("layers/{}/{}", repo_name, digest)
+    }
+
+    fn manifest_key(&self, repo_name: &str, reference: &str) -> String {
+        format!("manifests/{}/{}", repo_name, reference)
+    }
+
+    fn scratch_file(&self, uuid: &str) -> PathBuf {
+        self.scratch_path.join("scratch").join(uuid)
+    }
+
+    fn host(&self) -> String {
+        self.credentials
+            .endpoint
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/')
+            .to_owned()
+    }
+
+    fn bucket_url(&self) -> String {
+        format!("{}/{}", self.credentials.endpoint.trim_end_matches('/'), self.bucket)
+    }
+
+    /// Issues a SigV4-signed request for a single object (`key` relative to
+    /// the bucket) and maps the HTTP outcome onto the same `io::Result`
+    /// shape every other `Store` method uses: `Ok(None)` for a 404 (the
+    /// object doesn't exist, not an error), `Ok(Some(response))` for 2xx,
+    /// and `Err` for anything else, including a transport failure.
+    fn object_request(
+        &self,
+        method: Method,
+        key: &str,
+        body: Vec<u8>,
+    ) -> io::Result<Option<reqwest::Response>> {
+        let response = self.sign_and_send(method, key, "", body)?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("S3 request for {} failed: {}", key, response.status()),
+            ));
+        }
+        Ok(Some(response))
+    }
+
+    /// Signs and sends a path-style request against `/<bucket>/<key>`
+    /// (`key` empty means the bucket itself, e.g. for `ListObjectsV2`).
+    fn sign_and_send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &str,
+        body: Vec<u8>,
+    ) -> io::Result<reqwest::Response> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let (amz_date, date_stamp) = amz_date_strings(secs);
+        let payload_hash = sha256_hex(&body);
+        let host = self.host();
+
+        let canonical_uri = if key.is_empty() {
+            format!("/{}", self.bucket)
+        } else {
+            format!("/{}/{}", self.bucket, key)
+        };
+
+        let canonical_headers = format!(
+            "host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n",
+            host, payload_hash, amz_date
+        );
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method.as_str(),
+            canonical_uri,
+            query,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let credential_scope = format!("{}/{}/s3/aws4_request", date_stamp, self.credentials.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            credential_scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let signing_key = signing_key(&self.credentials.secret_key, &date_stamp, &self.credentials.region);
+        let signature = hex_encode(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key, credential_scope, signed_headers, signature
+        );
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.bucket_url(), if key.is_empty() { "".to_owned() } else { format!("/{}", key) })
+        } else {
+            format!("{}?{}", self.bucket_url(), query)
+        };
+
+        self.client
+            .request(method, &url)
+            .header("Host", host)
+            .header("X-Amz-Date", amz_date)
+            .header("X-Amz-Content-Sha256", payload_hash)
+            .header("Authorization", authorization)
+            .body(body)
+            .send()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("S3 request failed: {}", e)))
+    }
+}
+
+impl Store for S3Store {
+    fn blob_exists(&self, repo_name: &str, digest: &str) -> io::Result<bool> {
+        let key = self.blob_key(repo_name, digest);
+        Ok(self.object_request(Method::HEAD, &key, Vec::new())?.is_some())
+    }
+
+    fn read_blob(&self, repo_name: &str, digest: &str) -> io::Result<ByteStream> {
+        let key = self.blob_key(repo_name, digest);
+        let mut response = self
+            .object_request(Method::GET, &key, Vec::new())?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "blob not in repo"))?;
+        let mut bytes = Vec::new();
+        response
+            .copy_to(&mut bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("reading S3 body: {}", e)))?;
+        Ok(Box::new(futures::stream::once(Ok(bytes))))
+    }
+
+    fn write_blob_sink(&self, uuid: &str) -> io::Result<Box<dyn io::Write + Send>> {
+        let scratch_dir = self.scratch_path.join("scratch");
+        fs::create_dir_all(&scratch_dir)?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.scratch_file(uuid))?;
+        Ok(Box::new(file))
+    }
+
+    fn upload_size(&self, uuid: &str) -> io::Result<u64> {
+        match fs::metadata(self.scratch_file(uuid)) {
+            Ok(meta) => Ok(meta.len()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn abort_upload(&self, uuid: &str) -> io::Result<()> {
+        match fs::remove_file(self.scratch_file(uuid)) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn finalize_blob(
+        &self,
+        repo_name: &str,
+        uuid: &str,
+        expected_digest: &str,
+    ) -> io::Result<FinalizeOutcome> {
+        let scratch = self.scratch_file(uuid);
+
+        let mut hasher = Sha256::new();
+        let mut file = fs::File::open(&scratch)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        hasher.input(&bytes);
+        let actual_digest = format!("sha256:{}", hasher.result_str());
+
+        if actual_digest != expected_digest {
+            fs::remove_file(&scratch)?;
+            return Ok(FinalizeOutcome::DigestMismatch);
+        }
+
+        let key = self.blob_key(repo_name, expected_digest);
+        self.object_request(Method::PUT, &key, bytes)?;
+        fs::remove_file(&scratch)?;
+        Ok(FinalizeOutcome::Ok)
+    }
+
+    fn blob_size(&self, repo_name: &str, digest: &str) -> io::Result<u64> {
+        let key = self.blob_key(repo_name, digest);
+        let response = self
+            .object_request(Method::HEAD, &key, Vec::new())?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "blob not in repo"))?;
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "S3 HEAD missing Content-Length"))
+    }
+
+    fn delete_blob(&self, repo_name: &str, digest: &str) -> io::Result<()> {
+        let key = self.blob_key(repo_name, digest);
+        self.object_request(Method::DELETE, &key, Vec::new())?;
+        Ok(())
+    }
+
+    fn manifest_exists(&self, repo_name: &str, reference: &str) -> io::Result<bool> {
+        let key = self.manifest_key(repo_name, reference);
+        Ok(self.object_request(Method::HEAD, &key, Vec::new())?.is_some())
+    }
+
+    fn read_manifest(&self, repo_name: &str, reference: &str) -> io::Result<ByteStream> {
+        let key = self.manifest_key(repo_name, reference);
+        let mut response = self
+            .object_request(Method::GET, &key, Vec::new())?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "manifest not found"))?;
+        let mut bytes = Vec::new();
+        response
+            .copy_to(&mut bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("reading S3 body: {}", e)))?;
+        Ok(Box::new(futures::stream::once(Ok(bytes))))
+    }
+
+    fn write_manifest(&self, repo_name: &str, reference: &str, bytes: &[u8]) -> io::Result<()> {
+        let key = self.manifest_key(repo_name, reference);
+        self.object_request(Method::PUT, &key, bytes.to_vec())?;
+        Ok(())
+    }
+
+    fn delete_manifest(&self, repo_name: &str, reference: &str) -> io::Result<()> {
+        let key = self.manifest_key(repo_name, reference);
+        self.object_request(Method::DELETE, &key, Vec::new())?;
+        Ok(())
+    }
+
+    /*
+    Same mark-and-sweep as `FileStore::garbage_collect`, but walking the
+    bucket via `ListObjectsV2` instead of a local directory tree: list every
+    `manifests/` object, GET and scan each one for referenced digests (reusing
+    `extract_digests`), then list every `layers/` object and remove any whose
+    digest wasn't referenced and whose `LastModified` is older than
+    `GC_GRACE_PERIOD_SECS`.
+    */
+    fn garbage_collect(&self) -> io::Result<GcReport> {
+        let mut referenced = HashSet::new();
+        for key in self.list_objects("manifests/")? {
+            let mut response = self
+                .object_request(Method::GET, &key, Vec::new())?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "manifest listed but missing"))?;
+            let mut bytes = Vec::new();
+            response
+                .copy_to(&mut bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("reading S3 body: {}", e)))?;
+            if let Ok(text) = String::from_utf8(bytes) {
+                referenced.extend(extract_digests(&text));
+            }
+        }
+
+        let mut removed = Vec::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        for (key, last_modified) in self.list_objects_with_last_modified("layers/")? {
+            let digest = match key.rsplit('/').next() {
+                Some(d) if d.starts_with("sha256:") => d.to_owned(),
+                _ => continue,
+            };
+            let age = now.saturating_sub(last_modified);
+            if referenced.contains(&digest) || age < GC_GRACE_PERIOD_SECS {
+                continue;
+            }
+            self.object_request(Method::DELETE, &key, Vec::new())?;
+            removed.push(digest);
+        }
+
+        Ok(GcReport {
+            blobs_removed: removed,
+        })
+    }
+}
+
+impl S3Store {
+    fn list_objects(&self, prefix: &str) -> io::Result<Vec<String>> {
+        Ok(self
+            .list_objects_with_last_modified(prefix)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// `ListObjectsV2` against the bucket, filtered to `prefix`, following
+    /// `NextContinuationToken` until every page has been read. Parsed with
+    /// ad hoc tag scanning (`xml_tag_values`) rather than a full XML parser,
+    /// since each `<Contents>` entry's shape is fixed and known ahead of time.
+    fn list_objects_with_last_modified(&self, prefix: &str) -> io::Result<Vec<(String, u64)>> {
+        let mut objects = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            // SigV4 canonical query parameters must be sorted by name.
+            let query = match continuation {
+                Some(ref token) => format!(
+                    "continuation-token={}&list-type=2&prefix={}",
+                    percent_encode(token),
+                    percent_encode(prefix)
+                ),
+                None => format!("list-type=2&prefix={}", percent_encode(prefix)),
+            };
+            let mut response = self.sign_and_send(Method::GET, "", &query, Vec::new())?;
+            if !response.status().is_success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("S3 ListObjectsV2 failed: {}", response.status()),
+                ));
+            }
+
+            let mut body = String::new();
+            response
+                .read_to_string(&mut body)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("reading S3 body: {}", e)))?;
+
+            let keys = xml_tag_values(&body, "Key");
+            let last_modifieds = xml_tag_values(&body, "LastModified");
+            objects.extend(
+                keys.into_iter()
+                    .zip(last_modifieds.into_iter())
+                    .map(|(key, lm)| (key, parse_iso8601_to_unix(&lm).unwrap_or(0))),
+            );
+
+            continuation = if xml_tag_values(&body, "IsTruncated").first().map(|s| s.as_str()) == Some("true") {
+                xml_tag_values(&body, "NextContinuationToken").into_iter().next()
+            } else {
+                None
+            };
+            if continuation.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    hasher.result_str()
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), key);
+    mac.input(msg);
+    mac.result().code().to_vec()
+}
+
+/// AWS SigV4 signing key: four chained HMACs over the secret key, the date,
+/// the region, and the fixed strings "s3"/"aws4_request", so a leaked
+/// signature for one day/region can't be replayed to sign requests for
+/// another.
+fn signing_key(secret_key: &str, date_stamp: &str, region: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date_stamp.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, b"s3");
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Days-since-epoch -> proleptic Gregorian (year, month, day), and the
+/// inverse, using Howard Hinnant's `civil_from_days`/`days_from_civil`
+/// algorithms. Hand-rolled rather than pulling in a date/time crate for the
+/// handful of timestamps SigV4 and `ListObjectsV2` need.
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = if m <= 2 { y + 1 } else { y };
+    (y, m, d)
+}
+
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = if m > 2 { m - 3 } else { m + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn amz_date_strings(secs: u64) -> (String, String) {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (y, m, d) = civil_from_days(days);
+    let amz_date = format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        y,
+        m,
+        d,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    );
+    let date_stamp = format!("{:04}{:02}{:02}", y, m, d);
+    (amz_date, date_stamp)
+}
+
+fn parse_iso8601_to_unix(s: &str) -> Option<u64> {
+    let year: i64 = s.get(0..4)?.parse().ok()?;
+    let month: u32 = s.get(5..7)?.parse().ok()?;
+    let day: u32 = s.get(8..10)?.parse().ok()?;
+    let hour: u64 = s.get(11..13)?.parse().ok()?;
+    let min: u64 = s.get(14..16)?.parse().ok()?;
+    let sec: u64 = s.get(17..19)?.parse().ok()?;
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    Some(days as u64 * 86400 + hour * 3600 + min * 60 + sec)
+}
+
+fn xml_tag_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        if let Some(end) = rest.find(&close) {
+            values.push(rest[..end].to_owned());
+            rest = &rest[end + close.len()..];
+        } else {
+            break;
+        }
+    }
+    values
+}
@@ -0,0 +1,154 @@
+use std::io;
+use std::sync::Arc;
+
+use trow_server::{ByteStream, FinalizeOutcome, GcReport, Store};
+use types::create_upload_info;
+use response::errors::Error;
+use response::upload_info::UploadInfo;
+use uuid::Uuid;
+
+/// Default HMAC key for signing Bearer tokens, used only if the caller
+/// doesn't configure one explicitly. Every default deployment would mint
+/// forgeable tokens if this were ever used outside local development, so
+/// callers embedding Trow should always supply their own.
+pub const DEFAULT_TOKEN_SECRET: &'static str = "trow-dev-token-secret";
+
+/// Default Bearer challenge realm, used only if the caller doesn't
+/// configure one explicitly; correct for a local single-instance
+/// deployment, wrong for anything reachable at another hostname.
+pub const DEFAULT_REALM: &'static str = "http://localhost:5000/v2/token";
+
+/*
+What route handlers hold as `rocket::State`. Wraps the `Store` that actually
+owns blob bytes (see trow_server::store), so blob handlers never build a
+`data/...` path themselves. Manifest, tag-list and catalog handlers in
+`routes` still read and write `data/manifests` directly.
+*/
+pub struct ClientInterface {
+    store: Arc<dyn Store>,
+    max_upload_bytes: u64,
+    token_secret: String,
+    realm: String,
+}
+
+impl ClientInterface {
+    pub fn new(
+        store: Arc<dyn Store>,
+        max_upload_bytes: u64,
+        token_secret: String,
+        realm: String,
+    ) -> ClientInterface {
+        ClientInterface {
+            store,
+            max_upload_bytes,
+            token_secret,
+            realm,
+        }
+    }
+
+    /// Cap on the size of a single blob or manifest upload, in bytes; pushes
+    /// exceeding it are rejected with `Error::SizeLimitExceeded`.
+    pub fn max_upload_bytes(&self) -> u64 {
+        self.max_upload_bytes
+    }
+
+    /// HMAC key `routes::sign` uses to mint/verify Bearer tokens. Configured
+    /// per deployment rather than a baked-in literal, since a shared default
+    /// would let anyone forge a token against any Trow instance using it.
+    pub fn token_secret(&self) -> &str {
+        &self.token_secret
+    }
+
+    /// Realm advertised in the `WWW-Authenticate: Bearer` challenge, i.e.
+    /// where clients should exchange credentials for a token. Must match
+    /// the hostname Trow is actually reachable at.
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    /// Discards an upload's scratch data once it's been rejected, e.g. for
+    /// exceeding `max_upload_bytes`, so the partial bytes don't linger.
+    pub fn abort_upload(&self, _repo_name: &str, uuid: &str) -> io::Result<()> {
+        self.store.abort_upload(uuid)
+    }
+
+    /// Allocates a fresh upload uuid and touches its scratch file, so the
+    /// first chunked PATCH can query `current_size()` without special-casing
+    /// "upload just started".
+    pub fn request_upload(&self, repo_name: &str) -> io::Result<UploadInfo> {
+        let uuid = Uuid::new_v4().to_string();
+        let _ = self.store.write_blob_sink(&uuid)?;
+        Ok(create_upload_info(uuid, repo_name.to_owned(), (0, 0)))
+    }
+
+    pub fn get_write_sink_for_upload(
+        &self,
+        _repo_name: &str,
+        uuid: &str,
+    ) -> io::Result<UploadSink> {
+        let current_size = self.store.upload_size(uuid)?;
+        let writer = self.store.write_blob_sink(uuid)?;
+        Ok(UploadSink {
+            writer,
+            current_size: current_size as u32,
+        })
+    }
+
+    pub fn blob_exists(&self, repo_name: &str, digest: &str) -> io::Result<bool> {
+        self.store.blob_exists(repo_name, digest)
+    }
+
+    pub fn read_blob(&self, repo_name: &str, digest: &str) -> io::Result<ByteStream> {
+        self.store.read_blob(repo_name, digest)
+    }
+
+    pub fn blob_size(&self, repo_name: &str, digest: &str) -> io::Result<u64> {
+        self.store.blob_size(repo_name, digest)
+    }
+
+    pub fn delete_blob(&self, repo_name: &str, digest: &str) -> io::Result<()> {
+        self.store.delete_blob(repo_name, digest)
+    }
+
+    /// Hashes the bytes written via `get_write_sink_for_upload` and, on a
+    /// match, links them into `repo_name` under `digest`; on a mismatch
+    /// the upload is discarded and `Error::DigestInvalid` is returned so
+    /// the handler can respond the same way it would to any other bad
+    /// push, rather than a generic I/O failure.
+    pub fn complete_blob_upload(&self, repo_name: &str, uuid: &str, digest: &str) -> Result<(), Error> {
+        match self.store.finalize_blob(repo_name, uuid, digest) {
+            Ok(FinalizeOutcome::Ok) => Ok(()),
+            Ok(FinalizeOutcome::DigestMismatch) => Err(Error::DigestInvalid),
+            Err(_) => Err(Error::InternalError),
+        }
+    }
+
+    pub fn garbage_collect(&self) -> io::Result<GcReport> {
+        self.store.garbage_collect()
+    }
+}
+
+/// The write half of an in-progress blob upload, plus the byte offset it
+/// already held when this sink was opened (queried once up front rather
+/// than tracked as writes happen, since `patch_blob` only needs it to
+/// validate/report the `Content-Range` of the chunk it's about to accept).
+pub struct UploadSink {
+    writer: Box<dyn io::Write + Send>,
+    current_size: u32,
+}
+
+impl UploadSink {
+    pub fn current_size(&self) -> io::Result<u32> {
+        Ok(self.current_size)
+    }
+}
+
+impl io::Write for UploadSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
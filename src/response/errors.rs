@@ -0,0 +1,61 @@
+use std::io::Cursor;
+
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{Responder, Response};
+
+/*
+Errors a route handler can return. `respond_to` maps each onto the HTTP
+status code (and OCI distribution spec error code) Docker/containerd
+clients key their retry/abort behaviour off of.
+*/
+#[derive(Debug)]
+pub enum Error {
+    InternalError,
+    Unsupported,
+    ManifestInvalid,
+    ManifestUnknown,
+    BlobUnknown,
+    DigestInvalid,
+    RequestedRangeNotSatisfiable,
+    SizeLimitExceeded,
+}
+
+impl Error {
+    fn status(&self) -> Status {
+        match *self {
+            Error::InternalError => Status::InternalServerError,
+            Error::Unsupported => Status::MethodNotAllowed,
+            Error::ManifestInvalid => Status::BadRequest,
+            Error::ManifestUnknown => Status::NotFound,
+            Error::BlobUnknown => Status::NotFound,
+            Error::DigestInvalid => Status::BadRequest,
+            Error::RequestedRangeNotSatisfiable => Status::RangeNotSatisfiable,
+            Error::SizeLimitExceeded => Status::PayloadTooLarge,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match *self {
+            Error::InternalError => "UNKNOWN",
+            Error::Unsupported => "UNSUPPORTED",
+            Error::ManifestInvalid => "MANIFEST_INVALID",
+            Error::ManifestUnknown => "MANIFEST_UNKNOWN",
+            Error::BlobUnknown => "BLOB_UNKNOWN",
+            Error::DigestInvalid => "DIGEST_INVALID",
+            Error::RequestedRangeNotSatisfiable => "RANGE_INVALID",
+            Error::SizeLimitExceeded => "SIZE_INVALID",
+        }
+    }
+}
+
+impl<'r> Responder<'r> for Error {
+    fn respond_to(self, _req: &Request) -> Result<Response<'r>, Status> {
+        let body = format!("{{\"errors\":[{{\"code\":\"{}\"}}]}}", self.code());
+        Response::build()
+            .status(self.status())
+            .header(Header::new("Content-Type", "application/json"))
+            .sized_body(Cursor::new(body))
+            .ok()
+    }
+}
@@ -1,37 +1,49 @@
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::str;
 
-use client_interface::ClientInterface;
+use client_interface::{ClientInterface, DEFAULT_REALM};
 use crypto::digest::Digest;
 use crypto::sha2::Sha256;
-use manifest::{self, FromJson, Manifest};
+use manifest::{self, FromJson};
 use response::accepted_upload::{AcceptedUpload, create_accepted_upload};
 use response::empty::Empty;
 use response::errors::Error;
 use response::html::HTML;
 use response::manifest_upload::ManifestUpload;
 use response::upload_info::UploadInfo;
+use rocket::http::Header;
 use rocket::request::{self, FromRequest, Request};
-use rocket::response::NamedFile;
+use rocket::response::Responder;
 use rocket::{self, Outcome};
+use rustc_serialize::base64::FromBase64;
 use serde_json;
-use types::{self, create_upload_info};
+use types::create_upload_info;
 
 static DATA_DIR: &'static str = "data";
 static MANIFESTS_DIR: &'static str = "manifests";
-static LAYERS_DIR: &'static str = "layers";
 
 pub fn routes() -> Vec<rocket::Route> {
     routes![
         get_v2root,
         get_homepage,
+        get_token,
         get_manifest,
         get_manifest_2level,
         get_manifest_3level,
+        head_manifest,
+        head_manifest_2level,
+        head_manifest_3level,
+        get_catalog,
+        get_image_tags,
+        get_image_tags_2level,
+        get_image_tags_3level,
         get_blob,
         get_blob_qualified,
+        head_blob,
+        head_blob_qualified,
+        head_blob_qualified_3level,
         put_blob_qualified_3level,
         get_blob_qualified_3level,
         patch_blob_qualified_3level,
@@ -46,14 +58,18 @@ pub fn routes() -> Vec<rocket::Route> {
         put_image_manifest_qualified,
         put_image_manifest_qualified_3level,
         delete_image_manifest,
+        delete_image_manifest_qualified,
+        delete_image_manifest_qualified_3level,
+        delete_blob,
+        delete_blob_qualified,
+        delete_blob_qualified_3level,
+        garbage_collect,
     ]
     /* The following routes used to have stub methods, but I removed them as they were cluttering the code
           post_blob_uuid,
           get_upload_progress,
           delete_upload,
           delete_blob,
-          get_catalog,
-          get_image_tags,
           admin routes,
           admin_get_uuids
 
@@ -62,13 +78,314 @@ pub fn routes() -> Vec<rocket::Route> {
     */
 }
 
-struct AuthorisedUser(String);
+/*
+ * Response for the HEAD variants of the blob/manifest routes: no body, just
+ * the headers Docker/containerd check before deciding whether to push.
+ */
+struct ExistsResponse {
+    content_length: u64,
+    digest: String,
+}
+
+impl<'r> Responder<'r> for ExistsResponse {
+    fn respond_to(self, _req: &Request) -> Result<rocket::Response<'r>, rocket::http::Status> {
+        rocket::Response::build()
+            .header(Header::new(
+                "Content-Length",
+                self.content_length.to_string(),
+            ))
+            .header(Header::new("Docker-Content-Digest", self.digest))
+            .ok()
+    }
+}
+
+/*
+ * JSON body response for the catalog/tags-list routes, with an optional
+ * RFC5988 `Link` header for pagination continuation.
+ */
+struct JsonResponse {
+    body: String,
+    link: Option<String>,
+}
+
+impl<'r> Responder<'r> for JsonResponse {
+    fn respond_to(self, _req: &Request) -> Result<rocket::Response<'r>, rocket::http::Status> {
+        let mut builder = rocket::Response::build();
+        builder
+            .header(Header::new("Content-Type", "application/json"))
+            .sized_body(std::io::Cursor::new(self.body));
+        if let Some(link) = self.link {
+            builder.header(Header::new("Link", link));
+        }
+        builder.ok()
+    }
+}
+
+fn json_string_array(items: &[String]) -> String {
+    let quoted: Vec<String> = items.iter().map(|i| format!("\"{}\"", i)).collect();
+    format!("[{}]", quoted.join(","))
+}
+
+/*
+ * Sorts `items` lexically, drops everything up to and including `last` (the
+ * pagination marker), then truncates to `n` entries. Returns the page along
+ * with whether more entries remain beyond it.
+ */
+fn paginate(mut items: Vec<String>, n: Option<usize>, last: Option<String>) -> (Vec<String>, bool) {
+    items.sort();
+
+    if let Some(last) = last {
+        items = items.into_iter().filter(|i| *i > last).collect();
+    }
+
+    match n {
+        Some(n) if items.len() > n => {
+            items.truncate(n);
+            (items, true)
+        }
+        _ => (items, false),
+    }
+}
+
+static TOKEN_TTL_SECS: u64 = 300;
+static HTPASSWD_PATH: &'static str = "data/htpasswd";
+
+/*
+Parses `Authorization: Basic`/`Authorization: Bearer` and validates either
+against the htpasswd-style user table or a signed token minted by
+`get_token`. Missing/invalid credentials fail the guard, which the 401
+catcher below turns into the Bearer challenge Docker clients expect.
+
+A Bearer token carries the scope it was minted for (see `get_token`); a
+request presenting one is only authorised if that scope matches the
+resource its path actually names, so a token scoped to `repository:a:*`
+can't be replayed against `repository:b`. Basic credentials authenticate
+the user directly rather than via a pre-scoped token, so they aren't
+restricted to a scope.
+*/
+struct AuthorisedUser {
+    user: String,
+    scope: Option<String>,
+}
+
 impl<'a, 'r> FromRequest<'a, 'r> for AuthorisedUser {
     type Error = ();
-    fn from_request(_req: &'a Request<'r>) -> request::Outcome<AuthorisedUser, ()> {
-        Outcome::Success(AuthorisedUser("test".to_owned()))
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<AuthorisedUser, ()> {
+        let ci = match req.guard::<rocket::State<ClientInterface>>() {
+            Outcome::Success(ci) => ci,
+            _ => return Outcome::Failure((rocket::http::Status::InternalServerError, ())),
+        };
+
+        let header = match req.headers().get_one("Authorization") {
+            Some(h) => h,
+            None => return Outcome::Failure((rocket::http::Status::Unauthorized, ())),
+        };
+
+        let result = if header.starts_with("Basic ") {
+            check_basic_auth(&header[6..]).map(|user| (user, None))
+        } else if header.starts_with("Bearer ") {
+            verify_token(ci.token_secret(), &header[7..])
+        } else {
+            None
+        };
+
+        match result {
+            Some((user, Some(scope))) => {
+                let required = derive_scope(req.uri().path());
+                if scope != required {
+                    warn!(
+                        "Token for {} scoped to '{}' cannot access '{}'",
+                        user, scope, required
+                    );
+                    return Outcome::Failure((rocket::http::Status::Forbidden, ()));
+                }
+                Outcome::Success(AuthorisedUser { user, scope: Some(scope) })
+            }
+            Some((user, None)) => Outcome::Success(AuthorisedUser { user, scope: None }),
+            None => Outcome::Failure((rocket::http::Status::Unauthorized, ())),
+        }
+    }
+}
+
+/*
+ * `data/htpasswd` holds `<user>:<sha256 hex of password>` lines, one per
+ * user. Not htpasswd's own crypt/bcrypt format, but the same shape, and all
+ * the crypto primitives this crate already depends on support.
+ */
+fn check_basic_auth(encoded: &str) -> Option<String> {
+    let decoded = encoded.from_base64().ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let mut parts = decoded.splitn(2, ':');
+    let user = parts.next()?.to_owned();
+    let pass = parts.next()?;
+
+    let entries = fs::read_to_string(HTPASSWD_PATH).ok()?;
+    for line in entries.lines() {
+        let mut fields = line.splitn(2, ':');
+        let line_user = fields.next()?;
+        let line_hash = fields.next()?;
+        if line_user == user && line_hash == sha256_hex(pass.as_bytes()) {
+            return Some(user);
+        }
+    }
+    None
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    hasher.result_str()
+}
+
+fn current_unix_time() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/*
+ * Token shape is `<user>.<expiry>.<signature>.<scope>`, where signature is
+ * an HMAC-SHA256 over user+expiry+scope keyed on the deployment's
+ * `token_secret` (see `ClientInterface::token_secret`). A bare
+ * `sha256(secret || message)` is vulnerable to length-extension (an
+ * attacker can append to the message and compute a valid signature without
+ * knowing the secret); HMAC's inner/outer padding construction is immune
+ * to that.
+ *
+ * `scope` is serialized last, not in the middle, so it can itself contain
+ * `.` (as a repository name might, e.g. `registry.example.com/app`)
+ * without being ambiguous to split on: every field before it has a known,
+ * dot-free shape, so splitn's final capture is always the whole scope.
+ */
+fn sign(secret: &str, user: &str, expiry: u64, scope: &str) -> String {
+    use crypto::hmac::Hmac;
+    use crypto::mac::Mac;
+
+    let mut mac = Hmac::new(Sha256::new(), secret.as_bytes());
+    mac.input(format!("{}:{}:{}", user, expiry, scope).as_bytes());
+    mac.result()
+        .code()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn mint_token(secret: &str, user: &str, scope: &str) -> String {
+    let expiry = current_unix_time() + TOKEN_TTL_SECS;
+    let sig = sign(secret, user, expiry, scope);
+    format!("{}.{}.{}.{}", user, expiry, sig, scope)
+}
+
+fn verify_token(secret: &str, token: &str) -> Option<(String, Option<String>)> {
+    let mut parts = token.splitn(4, '.');
+    let user = parts.next()?.to_owned();
+    let expiry: u64 = parts.next()?.parse().ok()?;
+    let sig = parts.next()?.to_owned();
+    let scope = parts.next()?.to_owned();
+
+    if expiry < current_unix_time() {
+        return None;
+    }
+
+    let expected = sign(secret, &user, expiry, &scope);
+    if expected == sig {
+        Some((user, Some(scope)))
+    } else {
+        None
+    }
+}
+
+/*
+---
+Docker token-auth realm endpoint
+GET /v2/token?service=<service>&scope=<scope>
+
+Exchanges Basic credentials for a signed Bearer token, as required by the
+challenge issued on a 401.
+*/
+#[get("/v2/token?<_service>&<scope>")]
+fn get_token(
+    ci: rocket::State<ClientInterface>,
+    auth_user: AuthorisedUser,
+    _service: Option<String>,
+    scope: Option<String>,
+) -> JsonResponse {
+    // `auth_user` already passed the AuthorisedUser guard for this route's
+    // own (registry-wide) scope, so the principal is authenticated; the
+    // scope the client is requesting a token *for* is simply what gets
+    // baked into the token this mints, to be checked again on each request
+    // that presents it.
+    let scope = scope.unwrap_or_else(|| "registry:catalog:*".to_owned());
+    let token = mint_token(ci.token_secret(), &auth_user.user, &scope);
+    JsonResponse {
+        body: format!(
+            "{{\"token\":\"{}\",\"access_token\":\"{}\",\"expires_in\":{}}}",
+            token, token, TOKEN_TTL_SECS
+        ),
+        link: None,
+    }
+}
+
+/*
+ * Extracts a coarse `repository:<name>:pull,push` scope from the request
+ * path for the WWW-Authenticate challenge. Falls back to a registry-wide
+ * scope for routes like `_catalog` that aren't repo-scoped.
+ */
+fn derive_scope(path: &str) -> String {
+    let trimmed = path.trim_start_matches("/v2/");
+    for marker in &["/blobs/", "/manifests/"] {
+        if let Some(idx) = trimmed.find(marker) {
+            return format!("repository:{}:pull,push", &trimmed[..idx]);
+        }
+    }
+    "registry:catalog:*".to_owned()
+}
+
+/*
+ * Responds to a failed AuthorisedUser guard with the Bearer challenge
+ * Docker/containerd expect before they'll hit the token realm endpoint.
+ */
+struct BearerChallenge {
+    scope: String,
+    realm: String,
+}
+
+impl<'r> Responder<'r> for BearerChallenge {
+    fn respond_to(self, _req: &Request) -> Result<rocket::Response<'r>, rocket::http::Status> {
+        rocket::Response::build()
+            .status(rocket::http::Status::Unauthorized)
+            .header(Header::new(
+                "WWW-Authenticate",
+                format!(
+                    "Bearer realm=\"{}\",service=\"trow-registry\",scope=\"{}\"",
+                    self.realm, self.scope
+                ),
+            ))
+            .ok()
+    }
+}
+
+#[catch(401)]
+fn unauthorized(req: &Request) -> BearerChallenge {
+    // A catcher can't take `rocket::State` as a parameter the way a route
+    // handler can, but it can still fetch managed state off the request
+    // itself, which is how the realm gets here instead of a hard-coded
+    // literal.
+    let realm = match req.guard::<rocket::State<ClientInterface>>() {
+        Outcome::Success(ci) => ci.realm().to_owned(),
+        _ => DEFAULT_REALM.to_owned(),
+    };
+    BearerChallenge {
+        scope: derive_scope(req.uri().path()),
+        realm,
     }
 }
+
+pub fn catchers() -> Vec<rocket::Catcher> {
+    catchers![unauthorized]
+}
 /*
 Registry root.
 
@@ -88,6 +405,159 @@ fn get_homepage<'a>() -> HTML<'a> {
     HTML(ROOT_RESPONSE)
 }
 
+/*
+---
+Listing repositories
+GET /v2/_catalog
+
+Walks data/manifests to find every repository that has at least one stored
+manifest. Supports the distribution spec's `?n=<count>&last=<marker>`
+pagination, returning a `Link: <...>; rel="next"` header when more entries
+remain.
+*/
+#[get("/v2/_catalog?<n>&<last>")]
+fn get_catalog(n: Option<u32>, last: Option<String>, _auth_user: AuthorisedUser) -> JsonResponse {
+    let repos = list_repositories();
+    let (page, more) = paginate(repos, n.map(|v| v as usize), last);
+
+    let link = if more {
+        let last_item = page.last().cloned().unwrap_or_default();
+        let limit = n.unwrap_or_else(|| page.len() as u32);
+        Some(format!(
+            "</v2/_catalog?n={}&last={}>; rel=\"next\"",
+            limit, last_item
+        ))
+    } else {
+        None
+    };
+
+    JsonResponse {
+        body: format!("{{\"repositories\":{}}}", json_string_array(&page)),
+        link,
+    }
+}
+
+fn list_repositories() -> Vec<String> {
+    let mut repos = Vec::new();
+    let root = Path::new(DATA_DIR).join(MANIFESTS_DIR);
+    collect_repos(&root, "", &mut repos);
+    repos.sort();
+    repos
+}
+
+/*
+ * A directory under data/manifests is a repository if it contains manifest
+ * files directly; otherwise its children are further path segments of
+ * deeper repository names (e.g. org/user/repo).
+ */
+fn collect_repos(dir: &Path, prefix: &str, out: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut subdirs = Vec::new();
+    let mut has_files = false;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            subdirs.push(path);
+        } else {
+            has_files = true;
+        }
+    }
+
+    if has_files && !prefix.is_empty() {
+        out.push(prefix.to_string());
+    }
+
+    for subdir in subdirs {
+        let name = subdir.file_name().unwrap().to_string_lossy().into_owned();
+        let new_prefix = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        collect_repos(&subdir, &new_prefix, out);
+    }
+}
+
+/*
+---
+Listing tags
+GET /v2/<name>/tags/list
+
+Supports the same `?n=<count>&last=<marker>` pagination as `_catalog`.
+*/
+#[get("/v2/<name>/tags/list?<n>&<last>")]
+fn get_image_tags(
+    name: String,
+    n: Option<u32>,
+    last: Option<String>,
+    _auth_user: AuthorisedUser,
+) -> Option<JsonResponse> {
+    let dir = format!("{}/{}/{}", DATA_DIR, MANIFESTS_DIR, name);
+    let path = Path::new(&dir);
+    if !path.exists() {
+        return None;
+    }
+
+    let tags: Vec<String> = fs::read_dir(path)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| is_tag_name(name))
+        .collect();
+
+    let (page, more) = paginate(tags, n.map(|v| v as usize), last);
+
+    let link = if more {
+        let last_item = page.last().cloned().unwrap_or_default();
+        let limit = n.unwrap_or_else(|| page.len() as u32);
+        Some(format!(
+            "</v2/{}/tags/list?n={}&last={}>; rel=\"next\"",
+            name, limit, last_item
+        ))
+    } else {
+        None
+    };
+
+    Some(JsonResponse {
+        body: format!("{{\"name\":\"{}\",\"tags\":{}}}", name, json_string_array(&page)),
+        link,
+    })
+}
+
+/*
+ * Parse 2 level <user>/<repo> style path and pass it to get_image_tags
+ */
+#[get("/v2/<user>/<repo>/tags/list?<n>&<last>")]
+fn get_image_tags_2level(
+    user: String,
+    repo: String,
+    n: Option<u32>,
+    last: Option<String>,
+    auth_user: AuthorisedUser,
+) -> Option<JsonResponse> {
+    get_image_tags(format!("{}/{}", user, repo), n, last, auth_user)
+}
+
+/*
+ * Parse 3 level <org>/<user>/<repo> style path and pass it to get_image_tags
+ */
+#[get("/v2/<org>/<user>/<repo>/tags/list?<n>&<last>")]
+fn get_image_tags_3level(
+    org: String,
+    user: String,
+    repo: String,
+    n: Option<u32>,
+    last: Option<String>,
+    auth_user: AuthorisedUser,
+) -> Option<JsonResponse> {
+    get_image_tags(format!("{}/{}/{}", org, user, repo), n, last, auth_user)
+}
+
 /*
 ---
 Pulling an image
@@ -108,45 +578,119 @@ Accept: manifest-version
 200 - return the manifest
 404 - manifest not known to the registry
  */
-#[get("/v2/<onename>/manifests/<reference>")]
-fn get_manifest(onename: String, reference: String) -> Option<Manifest> {
-    let path = format!("{}/{}/{}/{}", DATA_DIR, MANIFESTS_DIR, onename, reference);
-    info!("Path: {}", path);
-    let path = Path::new(&path);
+/*
+ * `Accept`, parsed into the list of media types a client will take. Only one
+ * representation of each manifest is stored, so this does not choose between
+ * representations: `load_manifest` compares it with the stored media type
+ * and only logs a mismatch. An absent or empty header accepts anything.
+ */
+struct AcceptHeader(Vec<String>);
 
-    //Parse the manifest to get the response type
-    //We could do this faster by storing in appropriate folder and streaming file
-    //directly
-    if path.exists() {
-        return match fs::File::open(path) {
-            Ok(f) => serde_json::from_reader(f).ok(),
-            Err(_) => None,
-        };
+impl<'a, 'r> FromRequest<'a, 'r> for AcceptHeader {
+    type Error = ();
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<AcceptHeader, ()> {
+        let types = req
+            .headers()
+            .get("Accept")
+            .flat_map(|raw| raw.split(','))
+            .map(|part| part.split(';').next().unwrap_or("").trim().to_owned())
+            .collect();
+        Outcome::Success(AcceptHeader(types))
     }
+}
 
-    None
+impl AcceptHeader {
+    fn accepts(&self, media_type: &str) -> bool {
+        self.0.is_empty() || self.0.iter().any(|t| t == media_type || t == "*/*")
+    }
 }
 
-#[get("/v2/<user>/<repo>/manifests/<reference>")]
-fn get_manifest_2level(user: String, repo: String, reference: String) -> Option<Manifest> {
-    let path = format!(
-        "{}/{}/{}/{}/{}",
-        DATA_DIR, MANIFESTS_DIR, user, repo, reference
-    );
+static DEFAULT_MANIFEST_MEDIA_TYPE: &'static str =
+    "application/vnd.docker.distribution.manifest.v1+json";
+
+fn content_type_path(manifest_path: &str) -> String {
+    format!("{}.content-type", manifest_path)
+}
+
+/*
+ * A repo's manifest directory holds one entry per tag, plus the
+ * `.content-type` sidecar and `sha256:<digest>` alias `put_image_manifest`
+ * writes alongside it. Only the tag entries belong in a tags/list response.
+ */
+fn is_tag_name(name: &str) -> bool {
+    !name.ends_with(".content-type") && !name.contains(':')
+}
+
+/*
+ * Loads the manifest stored for `repo_name`/`reference`, along with the
+ * media type it was pushed with (recorded alongside it by
+ * `put_image_manifest`), and checks it against the client's `Accept` list.
+ */
+fn load_manifest(repo_name: &str, reference: &str, accept: &AcceptHeader) -> Option<ManifestResponse> {
+    let path = format!("{}/{}/{}/{}", DATA_DIR, MANIFESTS_DIR, repo_name, reference);
     info!("Path: {}", path);
-    let path = Path::new(&path);
 
-    //Parse the manifest to get the response type
-    //We could do this faster by storing in appropriate folder and streaming file
-    //directly
-    if path.exists() {
-        return match fs::File::open(path) {
-            Ok(f) => serde_json::from_reader(f).ok(),
-            Err(_) => None,
-        };
+    if !Path::new(&path).exists() {
+        return None;
     }
 
-    None
+    let bytes = fs::read(&path).ok()?;
+    let content_type = fs::read_to_string(content_type_path(&path))
+        .unwrap_or_else(|_| DEFAULT_MANIFEST_MEDIA_TYPE.to_owned());
+
+    // `reference` names one specific stored representation, not a family of
+    // equivalent ones to negotiate over, so an Accept mismatch here means
+    // the client's request was too narrow, not that the manifest is
+    // missing. Serve what's stored and let the client read the real
+    // Content-Type rather than 404ing an image that exists.
+    if !accept.accepts(&content_type) {
+        warn!(
+            "Client Accept header does not include stored media type {}; serving it anyway",
+            content_type
+        );
+    }
+
+    Some(ManifestResponse { bytes, content_type })
+}
+
+/*
+ * Serves the raw manifest bytes with the `Content-Type` it was pushed with,
+ * rather than re-serializing through a single fixed `Manifest` type, so
+ * schema2 manifests and manifest lists round-trip unchanged.
+ */
+struct ManifestResponse {
+    bytes: Vec<u8>,
+    content_type: String,
+}
+
+impl<'r> Responder<'r> for ManifestResponse {
+    fn respond_to(self, _req: &Request) -> Result<rocket::Response<'r>, rocket::http::Status> {
+        rocket::Response::build()
+            .header(Header::new("Content-Type", self.content_type))
+            .sized_body(std::io::Cursor::new(self.bytes))
+            .ok()
+    }
+}
+
+#[get("/v2/<onename>/manifests/<reference>")]
+fn get_manifest(
+    onename: String,
+    reference: String,
+    accept: AcceptHeader,
+    _auth_user: AuthorisedUser,
+) -> Option<ManifestResponse> {
+    load_manifest(&onename, &reference, &accept)
+}
+
+#[get("/v2/<user>/<repo>/manifests/<reference>")]
+fn get_manifest_2level(
+    user: String,
+    repo: String,
+    reference: String,
+    accept: AcceptHeader,
+    _auth_user: AuthorisedUser,
+) -> Option<ManifestResponse> {
+    load_manifest(&format!("{}/{}", user, repo), &reference, &accept)
 }
 
 /*
@@ -158,25 +702,56 @@ fn get_manifest_3level(
     user: String,
     repo: String,
     reference: String,
-) -> Option<Manifest> {
-    let path = format!(
-        "{}/{}/{}/{}/{}/{}",
-        DATA_DIR, MANIFESTS_DIR, org, user, repo, reference
-    );
-    info!("Path: {}", path);
+    accept: AcceptHeader,
+    _auth_user: AuthorisedUser,
+) -> Option<ManifestResponse> {
+    load_manifest(&format!("{}/{}/{}", org, user, repo), &reference, &accept)
+}
+
+/*
+ * HEAD variants of get_manifest/get_manifest_2level/get_manifest_3level.
+ * Docker/containerd use these to check whether a manifest already exists
+ * before pushing it, to avoid re-uploading unchanged content.
+ */
+#[head("/v2/<onename>/manifests/<reference>")]
+fn head_manifest(
+    onename: String,
+    reference: String,
+    _auth_user: AuthorisedUser,
+) -> Option<ExistsResponse> {
+    let path = format!("{}/{}/{}/{}", DATA_DIR, MANIFESTS_DIR, onename, reference);
     let path = Path::new(&path);
 
-    //Parse the manifest to get the response type
-    //We could do this faster by storing in appropriate folder and streaming file
-    //directly
-    if path.exists() {
-        return match fs::File::open(path) {
-            Ok(f) => serde_json::from_reader(f).ok(),
-            Err(_) => None,
-        };
+    if !path.exists() {
+        return None;
     }
 
-    None
+    let bytes = fs::read(path).ok()?;
+    Some(ExistsResponse {
+        content_length: bytes.len() as u64,
+        digest: gen_digest(&bytes),
+    })
+}
+
+#[head("/v2/<user>/<repo>/manifests/<reference>")]
+fn head_manifest_2level(
+    user: String,
+    repo: String,
+    reference: String,
+    auth_user: AuthorisedUser,
+) -> Option<ExistsResponse> {
+    head_manifest(format!("{}/{}", user, repo), reference, auth_user)
+}
+
+#[head("/v2/<org>/<user>/<repo>/manifests/<reference>")]
+fn head_manifest_3level(
+    org: String,
+    user: String,
+    repo: String,
+    reference: String,
+    auth_user: AuthorisedUser,
+) -> Option<ExistsResponse> {
+    head_manifest(format!("{}/{}/{}", org, user, repo), reference, auth_user)
 }
 
 /*
@@ -191,30 +766,55 @@ digest - unique identifier for the blob to be downoaded
 307 - redirect to another service for downloading[1]
  */
 
-#[get("/v2/<name_repo>/blobs/<digest>")]
-fn get_blob(name_repo: String, digest: String, _auth_user: AuthorisedUser) -> Option<NamedFile> {
-    let path = format!("{}/{}/{}/{}", DATA_DIR, LAYERS_DIR, name_repo, digest);
-    info!("Path: {}", path);
-    let path = Path::new(&path);
+/*
+ * Wraps the bytes `ClientInterface::read_blob` returns. Collected up front
+ * (the store's `ByteStream` is a futures 0.1 stream meant for the grpc
+ * server side) rather than streamed through the response, same tradeoff
+ * `ManifestResponse` already makes for manifest bytes.
+ */
+struct BlobResponse {
+    bytes: Vec<u8>,
+}
 
-    if path.exists() {
-        NamedFile::open(path).ok()
-    } else {
-        None
+impl<'r> Responder<'r> for BlobResponse {
+    fn respond_to(self, _req: &Request) -> Result<rocket::Response<'r>, rocket::http::Status> {
+        rocket::Response::build()
+            .header(Header::new("Content-Type", "application/octet-stream"))
+            .sized_body(std::io::Cursor::new(self.bytes))
+            .ok()
     }
 }
+
+fn collect_blob(ci: &ClientInterface, name_repo: &str, digest: &str) -> Option<Vec<u8>> {
+    use futures::Stream;
+
+    let stream = ci.read_blob(name_repo, digest).ok()?;
+    let chunks: Vec<Vec<u8>> = stream.wait().collect::<Result<_, _>>().ok()?;
+    Some(chunks.concat())
+}
+
+#[get("/v2/<name_repo>/blobs/<digest>")]
+fn get_blob(
+    ci: rocket::State<ClientInterface>,
+    name_repo: String,
+    digest: String,
+    _auth_user: AuthorisedUser,
+) -> Option<BlobResponse> {
+    collect_blob(&ci, &name_repo, &digest).map(|bytes| BlobResponse { bytes })
+}
 /*
  * Parse 2 level <repo>/<name> style path and pass it to get_blob
  */
 
 #[get("/v2/<name>/<repo>/blobs/<digest>")]
 fn get_blob_qualified(
+    ci: rocket::State<ClientInterface>,
     name: String,
     repo: String,
     digest: String,
     auth_user: AuthorisedUser,
-) -> Option<NamedFile> {
-    get_blob(format!("{}/{}", name, repo), digest, auth_user)
+) -> Option<BlobResponse> {
+    get_blob(ci, format!("{}/{}", name, repo), digest, auth_user)
 }
 
 /*
@@ -222,14 +822,61 @@ fn get_blob_qualified(
  */
 #[get("/v2/<org>/<name>/<repo>/blobs/<digest>")]
 fn get_blob_qualified_3level(
+    ci: rocket::State<ClientInterface>,
     org: String,
     name: String,
     repo: String,
     digest: String,
     auth_user: AuthorisedUser,
-) -> Option<NamedFile> {
-    get_blob(format!("{}/{}/{}", org, name, repo), digest, auth_user)
+) -> Option<BlobResponse> {
+    get_blob(ci, format!("{}/{}/{}", org, name, repo), digest, auth_user)
 }
+
+/*
+ * HEAD variants of get_blob/get_blob_qualified/get_blob_qualified_3level,
+ * used by Docker/containerd to check blob existence before pushing.
+ */
+#[head("/v2/<name_repo>/blobs/<digest>")]
+fn head_blob(
+    ci: rocket::State<ClientInterface>,
+    name_repo: String,
+    digest: String,
+    _auth_user: AuthorisedUser,
+) -> Option<ExistsResponse> {
+    if !ci.blob_exists(&name_repo, &digest).ok()? {
+        return None;
+    }
+
+    let content_length = ci.blob_size(&name_repo, &digest).ok()?;
+    Some(ExistsResponse {
+        content_length,
+        digest,
+    })
+}
+
+#[head("/v2/<name>/<repo>/blobs/<digest>")]
+fn head_blob_qualified(
+    ci: rocket::State<ClientInterface>,
+    name: String,
+    repo: String,
+    digest: String,
+    auth_user: AuthorisedUser,
+) -> Option<ExistsResponse> {
+    head_blob(ci, format!("{}/{}", name, repo), digest, auth_user)
+}
+
+#[head("/v2/<org>/<name>/<repo>/blobs/<digest>")]
+fn head_blob_qualified_3level(
+    ci: rocket::State<ClientInterface>,
+    org: String,
+    name: String,
+    repo: String,
+    digest: String,
+    auth_user: AuthorisedUser,
+) -> Option<ExistsResponse> {
+    head_blob(ci, format!("{}/{}/{}", org, name, repo), digest, auth_user)
+}
+
 /*
 ---
 Monolithic Upload
@@ -257,47 +904,15 @@ struct UploadQuery {
 
 #[put("/v2/<repo_name>/blobs/uploads/<uuid>?<query>")]
 fn put_blob(
-    _ci: rocket::State<ClientInterface>,
+    ci: rocket::State<ClientInterface>,
     repo_name: String,
     uuid: String,
     query: UploadQuery,
+    _auth_user: AuthorisedUser,
 ) -> Result<AcceptedUpload, Error> {
-
-         // 1. copy file to new location
-        //let backend = handler.backend();
-        let layer = types::Layer {
-            repo_name: repo_name.clone(),
-            digest: query.digest.clone(),
-        };
-        let digest_path = format!("data/layers/{}/{}", layer.repo_name, layer.digest);
-        let path = format!("data/layers/{}", layer.repo_name);
-        let scratch_path = format!("data/scratch/{}", uuid);
-        debug!("Saving file");
-        // 1.1 check direcory exists
-        if !Path::new(&path).exists() {
-            fs::create_dir_all(path).map_err(|_| Error::InternalError)?;
-        }
-        fs::copy(&scratch_path, digest_path).map_err(|_| Error::InternalError)?;
-        // 2. delete uploaded temporary file
-        debug!("Deleting file: {}", uuid);
-        fs::remove_file(scratch_path).map_err(|_| Error::InternalError)?;
-        Ok(create_accepted_upload(uuid, query.digest, repo_name))
-        // 3. delete uuid from the backend
-        // TODO is this process right? Should the backend be doing this?!
-        /*
-        let mut layer = server::Layer::new();
-        layer.set_repo_name(repo_name.clone());
-        layer.set_digest(uuid.clone());
-        let resp = backend.delete_uuid(&layer)?;
-        // 4. Construct response
-        if resp.get_success() {
-            Ok(create_accepted_upload(uuid, digest, repo_name))
-        } else {
-            warn!("Failed to remove UUID");
-            Err(failure::err_msg("Not implemented"))
-        }
-        */
-
+    debug!("Finalizing upload {} for {}", uuid, repo_name);
+    ci.complete_blob_upload(&repo_name, &uuid, &query.digest)?;
+    Ok(create_accepted_upload(uuid, query.digest, repo_name))
 }
 
 /*
@@ -310,8 +925,9 @@ fn put_blob_qualified(
     name: String,
     uuid: String,
     query: UploadQuery,
+    auth_user: AuthorisedUser,
 ) -> Result<AcceptedUpload, Error> {
-    put_blob(config, format!("{}/{}", repo, name), uuid, query)
+    put_blob(config, format!("{}/{}", repo, name), uuid, query, auth_user)
 }
 
 /*
@@ -325,15 +941,57 @@ fn put_blob_qualified_3level(
     name: String,
     uuid: String,
     query: UploadQuery,
+    auth_user: AuthorisedUser,
 ) -> Result<AcceptedUpload, Error> {
-    put_blob(config, format!("{}/{}/{}", org, repo, name), uuid, query)
+    put_blob(
+        config,
+        format!("{}/{}/{}", org, repo, name),
+        uuid,
+        query,
+        auth_user,
+    )
+}
+
+/*
+ * Content-Range: <start>-<end>, as sent by Docker/containerd on each chunked
+ * PATCH. Absent on the very first chunk of an upload; whenever it is absent,
+ * no offset check is made and the chunk is appended at the upload's current
+ * size.
+ */
+struct ContentRange {
+    start: u32,
+    end: u32,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ContentRange {
+    type Error = ();
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<ContentRange, ()> {
+        let header = match req.headers().get_one("Content-Range") {
+            Some(h) => h,
+            None => return Outcome::Forward(()),
+        };
+        let mut parts = header.splitn(2, '-');
+        let parsed = parts
+            .next()
+            .and_then(|s| s.parse::<u32>().ok())
+            .and_then(|start| parts.next().map(|end| (start, end)))
+            .and_then(|(start, end)| end.parse::<u32>().ok().map(|end| (start, end)));
+
+        match parsed {
+            Some((start, end)) => Outcome::Success(ContentRange { start, end }),
+            None => Outcome::Forward(()),
+        }
+    }
 }
 
 /*
 
-Uploads a blob or chunk of a blog.
+Uploads a blob or chunk of a blob.
 
-Checks UUID. Returns UploadInfo with range set to correct position.
+Checks UUID. Rejects the chunk with `RequestedRangeNotSatisfiable` if the
+`Content-Range` start does not match the current size of the upload, so
+that out-of-order or resumed chunks can't silently corrupt the blob.
+Returns UploadInfo with range set to the true byte offsets of the upload.
 
 */
 #[patch("/v2/<repo_name>/blobs/uploads/<uuid>", data = "<chunk>")]
@@ -341,19 +999,49 @@ fn patch_blob(
     ci: rocket::State<ClientInterface>,
     repo_name: String,
     uuid: String,
+    content_range: Option<ContentRange>,
     chunk: rocket::data::Data,
+    _auth_user: AuthorisedUser,
 ) -> Result<UploadInfo, Error> {
     let sink = ci.get_write_sink_for_upload(&repo_name, &uuid);
 
     match sink {
         Ok(mut sink) => {
-            //TODO: for the moment we'll just append, but this should seek to correct position
-            //according to spec shouldn't allow out-of-order uploads, so verify start address (from header)
-            //is same as current address
-            let len = chunk.stream_to(&mut sink);
-            match len {
-                //TODO: For chunked upload this should be start pos to end pos
-                Ok(len) => Ok(create_upload_info(uuid, repo_name, (0, len as u32))),
+            let current_size = sink.current_size().map_err(|_| Error::InternalError)?;
+
+            if let Some(range) = content_range {
+                if range.start != current_size {
+                    warn!(
+                        "Uuid {} got Content-Range start {} but current size is {}",
+                        uuid, range.start, current_size
+                    );
+                    let _ = chunk.stream_to_file("/dev/null");
+                    return Err(Error::RequestedRangeNotSatisfiable);
+                }
+            }
+
+            // Bounded against what's left of the budget, not a fresh
+            // max_upload_bytes per chunk: a `docker push` sends many chunks,
+            // and checking each one against the cap in isolation would let
+            // an upload grow without limit across enough of them.
+            let max_upload_bytes = ci.max_upload_bytes();
+            let remaining = max_upload_bytes.saturating_sub(u64::from(current_size));
+            let mut limited = chunk.open().take(remaining + 1);
+            let written = std::io::copy(&mut limited, &mut sink);
+
+            match written {
+                Ok(written) if written > remaining => {
+                    warn!(
+                        "Upload {} exceeded max_upload_bytes ({}), aborting",
+                        uuid, max_upload_bytes
+                    );
+                    let _ = ci.abort_upload(&repo_name, &uuid);
+                    Err(Error::SizeLimitExceeded)
+                }
+                Ok(written) => {
+                    let end = current_size + written as u32;
+                    Ok(create_upload_info(uuid, repo_name, (current_size, end)))
+                }
                 Err(_) => Err(Error::InternalError),
             }
         }
@@ -377,9 +1065,18 @@ fn patch_blob_qualified(
     repo: String,
     name: String,
     uuid: String,
+    content_range: Option<ContentRange>,
     chunk: rocket::data::Data,
+    auth_user: AuthorisedUser,
 ) -> Result<UploadInfo, Error> {
-    patch_blob(ci, format!("{}/{}", repo, name), uuid, chunk)
+    patch_blob(
+        ci,
+        format!("{}/{}", repo, name),
+        uuid,
+        content_range,
+        chunk,
+        auth_user,
+    )
 }
 
 /*
@@ -395,9 +1092,18 @@ fn patch_blob_qualified_3level(
     repo: String,
     name: String,
     uuid: String,
+    content_range: Option<ContentRange>,
     chunk: rocket::data::Data,
+    auth_user: AuthorisedUser,
 ) -> Result<UploadInfo, Error> {
-    patch_blob(handler, format!("{}/{}/{}", org, repo, name), uuid, chunk)
+    patch_blob(
+        handler,
+        format!("{}/{}/{}", org, repo, name),
+        uuid,
+        content_range,
+        chunk,
+        auth_user,
+    )
 }
 /*
   Starting point for an uploading a new image or new version of an image.
@@ -462,19 +1168,45 @@ PUT /v2/<name>/manifests/<reference>
 Content-Type: <manifest media type>
 
  */
+/*
+ * Content-Type of the pushed manifest, defaulting to docker schema1 when
+ * absent so older clients that don't set it keep working.
+ */
+struct ManifestContentType(String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ManifestContentType {
+    type Error = ();
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<ManifestContentType, ()> {
+        let media_type = req
+            .headers()
+            .get_one("Content-Type")
+            .unwrap_or(DEFAULT_MANIFEST_MEDIA_TYPE)
+            .to_owned();
+        Outcome::Success(ManifestContentType(media_type))
+    }
+}
+
 #[put("/v2/<repo_name>/manifests/<reference>", data = "<chunk>")]
 fn put_image_manifest(
+    ci: rocket::State<ClientInterface>,
     repo_name: String,
     reference: String,
     chunk: rocket::data::Data,
+    content_type: ManifestContentType,
+    _auth_user: AuthorisedUser,
 ) -> Result<ManifestUpload, Error> {
-    let mut manifest_bytes = Vec::new();
     //TODO From this point on, should stream to backend
     //Note that back end will need to have manifest, user, repo, ref
     //and possibly some sort of auth token
     //Needs to return digest & location or error
     //Just do this synchronous, let grpc deal with timeouts
-    chunk.stream_to(&mut manifest_bytes).unwrap();
+    let max_upload_bytes = ci.max_upload_bytes();
+    let mut manifest_bytes = Vec::new();
+    let mut limited = chunk.open().take(max_upload_bytes + 1);
+    let written = std::io::copy(&mut limited, &mut manifest_bytes).map_err(|_| Error::InternalError)?;
+    if written > max_upload_bytes {
+        return Err(Error::SizeLimitExceeded);
+    }
     // TODO: wouldn't shadowing be better here?
     let raw_manifest = str::from_utf8(&manifest_bytes).unwrap();
     let manifest_json: serde_json::Value = serde_json::from_str(raw_manifest).unwrap();
@@ -483,14 +1215,22 @@ fn put_image_manifest(
         Err(_) => return Err(Error::ManifestInvalid),
     };
 
-    for digest in manifest.get_asset_digests() {
-        let path = format!("{}/{}/{}/{}", DATA_DIR, LAYERS_DIR, repo_name, digest);
-        info!("Path: {}", path);
-        let path = Path::new(&path);
-
-        if !path.exists() {
-            warn!("Layer does not exist in repo");
-            return Err(Error::ManifestInvalid);
+    // A manifest list's assets are child manifest digests, not blobs, so
+    // they're checked against the manifest store rather than the blob store.
+    if let manifest::Manifest::List(_) = manifest {
+        for digest in manifest.get_asset_digests() {
+            let path = format!("{}/{}/{}/{}", DATA_DIR, MANIFESTS_DIR, repo_name, digest);
+            if !Path::new(&path).exists() {
+                warn!("Referenced child manifest does not exist in repo");
+                return Err(Error::ManifestInvalid);
+            }
+        }
+    } else {
+        for digest in manifest.get_asset_digests() {
+            if !ci.blob_exists(&repo_name, &digest).map_err(|_| Error::InternalError)? {
+                warn!("Layer does not exist in repo");
+                return Err(Error::ManifestInvalid);
+            }
         }
     }
 
@@ -500,11 +1240,23 @@ fn put_image_manifest(
 
     let manifest_directory = format!("{}/{}/{}/", DATA_DIR, MANIFESTS_DIR, repo_name);
     let manifest_path = format!("{}/{}", manifest_directory, reference);
-    fs::create_dir_all(manifest_directory).unwrap();
-    let mut file = fs::File::create(manifest_path).unwrap();
+    fs::create_dir_all(&manifest_directory).unwrap();
+    let mut file = fs::File::create(&manifest_path).unwrap();
     file.write_all(raw_manifest.as_bytes()).unwrap();
+    fs::write(content_type_path(&manifest_path), content_type.0.as_bytes()).unwrap();
 
     let digest = gen_digest(raw_manifest.as_bytes());
+
+    // `reference` is whatever the client pushed under (usually a tag); also
+    // write the manifest under its digest so a later delete-by-digest (which
+    // only ever sees the digest, not the tag it was pushed with) can find it.
+    if reference != digest {
+        let digest_path = format!("{}/{}", manifest_directory, digest);
+        fs::copy(&manifest_path, &digest_path).map_err(|_| Error::InternalError)?;
+        fs::write(content_type_path(&digest_path), content_type.0.as_bytes())
+            .map_err(|_| Error::InternalError)?;
+    }
+
     let location = format!(
         "http://localhost:5000/v2/{}/manifests/{}",
         repo_name, digest
@@ -518,12 +1270,22 @@ fn put_image_manifest(
  */
 #[put("/v2/<user>/<repo>/manifests/<reference>", data = "<chunk>")]
 fn put_image_manifest_qualified(
+    ci: rocket::State<ClientInterface>,
     user: String,
     repo: String,
     reference: String,
     chunk: rocket::data::Data,
+    content_type: ManifestContentType,
+    auth_user: AuthorisedUser,
 ) -> Result<ManifestUpload, Error> {
-    put_image_manifest(format!("{}/{}", user, repo), reference, chunk)
+    put_image_manifest(
+        ci,
+        format!("{}/{}", user, repo),
+        reference,
+        chunk,
+        content_type,
+        auth_user,
+    )
 }
 
 /*
@@ -534,13 +1296,23 @@ fn put_image_manifest_qualified(
     data = "<chunk>"
 )]
 fn put_image_manifest_qualified_3level(
+    ci: rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
     reference: String,
     chunk: rocket::data::Data,
+    content_type: ManifestContentType,
+    auth_user: AuthorisedUser,
 ) -> Result<ManifestUpload, Error> {
-    put_image_manifest(format!("{}/{}/{}", org, user, repo), reference, chunk)
+    put_image_manifest(
+        ci,
+        format!("{}/{}/{}", org, user, repo),
+        reference,
+        chunk,
+        content_type,
+        auth_user,
+    )
 }
 fn gen_digest(bytes: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -552,9 +1324,126 @@ fn gen_digest(bytes: &[u8]) -> String {
 ---
 Deleting an Image
 DELETE /v2/<name>/manifests/<reference>
+
+`reference` may be a tag or a digest; either way the file stored under it
+(and its content-type sidecar) is removed. The layer blobs it pointed at
+are left alone until `garbage_collect` confirms nothing else references
+them.
 */
+#[delete("/v2/<repo_name>/manifests/<reference>")]
+fn delete_image_manifest(
+    repo_name: String,
+    reference: String,
+    _auth_user: AuthorisedUser,
+) -> Result<Empty, Error> {
+    let path = format!("{}/{}/{}/{}", DATA_DIR, MANIFESTS_DIR, repo_name, reference);
+
+    if !Path::new(&path).exists() {
+        return Err(Error::ManifestUnknown);
+    }
+
+    fs::remove_file(&path).map_err(|_| Error::InternalError)?;
+    let _ = fs::remove_file(content_type_path(&path));
+    Ok(Empty)
+}
 
-#[delete("/v2/<_name>/<_repo>/manifests/<_reference>")]
-fn delete_image_manifest(_name: String, _repo: String, _reference: String) -> Result<Empty, Error> {
-    Err(Error::Unsupported)
+/*
+ * Parse 2 level <user>/<repo> style path and pass it to delete_image_manifest
+ */
+#[delete("/v2/<user>/<repo>/manifests/<reference>")]
+fn delete_image_manifest_qualified(
+    user: String,
+    repo: String,
+    reference: String,
+    auth_user: AuthorisedUser,
+) -> Result<Empty, Error> {
+    delete_image_manifest(format!("{}/{}", user, repo), reference, auth_user)
+}
+
+/*
+ * Parse 3 level <org>/<user>/<repo> style path and pass it to delete_image_manifest
+ */
+#[delete("/v2/<org>/<user>/<repo>/manifests/<reference>")]
+fn delete_image_manifest_qualified_3level(
+    org: String,
+    user: String,
+    repo: String,
+    reference: String,
+    auth_user: AuthorisedUser,
+) -> Result<Empty, Error> {
+    delete_image_manifest(format!("{}/{}/{}", org, user, repo), reference, auth_user)
+}
+
+/*
+---
+Deleting a Layer
+DELETE /v2/<name>/blobs/<digest>
+*/
+#[delete("/v2/<name_repo>/blobs/<digest>")]
+fn delete_blob(
+    ci: rocket::State<ClientInterface>,
+    name_repo: String,
+    digest: String,
+    _auth_user: AuthorisedUser,
+) -> Result<Empty, Error> {
+    if !ci.blob_exists(&name_repo, &digest).map_err(|_| Error::InternalError)? {
+        return Err(Error::BlobUnknown);
+    }
+
+    ci.delete_blob(&name_repo, &digest).map_err(|_| Error::InternalError)?;
+    Ok(Empty)
+}
+
+/*
+ * Parse 2 level <repo>/<name> style path and pass it to delete_blob
+ */
+#[delete("/v2/<name>/<repo>/blobs/<digest>")]
+fn delete_blob_qualified(
+    ci: rocket::State<ClientInterface>,
+    name: String,
+    repo: String,
+    digest: String,
+    auth_user: AuthorisedUser,
+) -> Result<Empty, Error> {
+    delete_blob(ci, format!("{}/{}", name, repo), digest, auth_user)
+}
+
+/*
+ * Parse 3 level <org>/<repo>/<name> style path and pass it to delete_blob
+ */
+#[delete("/v2/<org>/<name>/<repo>/blobs/<digest>")]
+fn delete_blob_qualified_3level(
+    ci: rocket::State<ClientInterface>,
+    org: String,
+    name: String,
+    repo: String,
+    digest: String,
+    auth_user: AuthorisedUser,
+) -> Result<Empty, Error> {
+    delete_blob(ci, format!("{}/{}/{}", org, name, repo), digest, auth_user)
+}
+
+/*
+---
+Garbage collection
+POST /v2/_trow/gc
+
+Delegates to the `Store`'s own mark-and-sweep, which guards against racing a
+concurrent upload with `GC_GRACE_PERIOD_SECS`. Exposed as a plain on-demand
+HTTP route.
+*/
+#[post("/v2/_trow/gc")]
+fn garbage_collect(
+    ci: rocket::State<ClientInterface>,
+    _auth_user: AuthorisedUser,
+) -> Result<JsonResponse, Error> {
+    let report = ci.garbage_collect().map_err(|_| Error::InternalError)?;
+
+    Ok(JsonResponse {
+        body: format!(
+            "{{\"blobs_removed\":{}}}",
+            json_string_array(&report.blobs_removed)
+        ),
+        link: None,
+    })
 }